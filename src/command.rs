@@ -5,11 +5,18 @@ use cyphernet::ed25519::PublicKey;
 
 use crate::RemoteHost;
 
-#[derive(Subcommand, Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
-#[display(lowercase)]
+#[derive(Subcommand, Clone, Eq, PartialEq, Hash, Debug, Display)]
 pub enum LocalCommand {
+    #[display("echo")]
     Echo,
+    #[display("date")]
     Date,
+    #[display("shell")]
+    Shell,
+    /// Run an arbitrary program on the remote host, streaming its stdout,
+    /// stderr and exit status back separately (see [`crate::exec`]).
+    #[display("exec:{program}")]
+    Exec { program: String, args: Vec<String> },
 }
 
 #[derive(Debug, Display, From, Error)]
@@ -29,7 +36,19 @@ impl FromStr for LocalCommand {
         Ok(match s {
             "echo" => LocalCommand::Echo,
             "date" => LocalCommand::Date,
-            _ => return Err(InvalidCommand::Unrecognized(s.to_owned())),
+            "shell" => LocalCommand::Shell,
+            _ => match s.strip_prefix("exec:") {
+                Some(rest) => {
+                    let mut words = rest.split_whitespace();
+                    let program = words
+                        .next()
+                        .ok_or_else(|| InvalidCommand::Unrecognized(s.to_owned()))?
+                        .to_owned();
+                    let args = words.map(str::to_owned).collect();
+                    LocalCommand::Exec { program, args }
+                }
+                None => return Err(InvalidCommand::Unrecognized(s.to_owned())),
+            },
         })
     }
 }
@@ -52,6 +71,9 @@ impl Command {
     pub const DATE: Command = Command::Execute {
         command: LocalCommand::Date,
     };
+    pub const SHELL: Command = Command::Execute {
+        command: LocalCommand::Shell,
+    };
 }
 
 impl FromStr for Command {
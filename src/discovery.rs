@@ -0,0 +1,403 @@
+//! Kademlia-style peer discovery layered on top of established noise
+//! sessions.
+//!
+//! Each node keeps a [`RoutingTable`] of `(PublicKey, NetAddr)` entries
+//! bucketed by XOR distance between the 256-bit public keys, and answers
+//! `PING`/`FIND_NODE` requests from peers it is already talking to. This
+//! lets a `Connect` that only has a bare public key resolve it to a
+//! reachable address without the user supplying one.
+
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use cyphernet::addr::{InetHost, NetAddr};
+use cyphernet::ed25519::PublicKey;
+
+/// Closest-`k` entries returned per `FIND_NODE`, as in the original Kademlia
+/// paper.
+pub const K: usize = 8;
+/// One bucket per bit of the 256-bit node id.
+const NUM_BUCKETS: usize = 256;
+
+/// XOR distance metric between two node ids, used both to pick a bucket and
+/// to order `FIND_NODE` results by closeness.
+pub fn distance(a: &PublicKey, b: &PublicKey) -> [u8; 32] {
+    let a = a.to_pk_compressed();
+    let b = b.to_pk_compressed();
+    let mut out = [0u8; 32];
+    for i in 0..32 {
+        out[i] = a[i] ^ b[i];
+    }
+    out
+}
+
+/// Index of the highest set bit in `distance(a, b)`, i.e. which bucket an
+/// entry for `b` belongs in from `a`'s perspective. Returns `None` if `a ==
+/// b` (never inserted into the table).
+fn bucket_index(a: &PublicKey, b: &PublicKey) -> Option<usize> {
+    let d = distance(a, b);
+    for (byte_idx, byte) in d.iter().enumerate() {
+        if *byte != 0 {
+            let bit_in_byte = 7 - byte.leading_zeros() as usize;
+            return Some(NUM_BUCKETS - 1 - (byte_idx * 8 + (7 - bit_in_byte)));
+        }
+    }
+    None
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct NodeEntry {
+    pub id: PublicKey,
+    pub addr: NetAddr<InetHost>,
+}
+
+/// A size-bounded, least-recently-seen-first bucket of [`NodeEntry`]s.
+#[derive(Default, Debug)]
+struct Bucket {
+    entries: VecDeque<NodeEntry>,
+}
+
+impl Bucket {
+    /// Mark `entry` as freshly seen: move it to the back if already present,
+    /// otherwise push it on if there's room. A new entry arriving at a full
+    /// bucket is dropped; the least-recently-seen entry is only removed by
+    /// [`RoutingTable::evict_stale`]. No liveness `PING` is sent before that
+    /// removal: `Server`'s `tick` evicts a candidate that is not currently
+    /// connected.
+    fn seen(&mut self, entry: NodeEntry) {
+        self.entries.retain(|e| e.id != entry.id);
+        if self.entries.len() < K {
+            self.entries.push_back(entry);
+        }
+    }
+
+    fn least_recently_seen(&self) -> Option<&NodeEntry> {
+        self.entries.front()
+    }
+
+    fn evict_least_recently_seen(&mut self) {
+        self.entries.pop_front();
+    }
+}
+
+/// Routing table of known peers, bucketed by XOR distance from `self_id`.
+///
+/// Every mutating [`Self::insert`] auto-persists the table to `path`, much
+/// as [`crate::reputation::ReputationTracker::penalize`] saves the ban list
+/// each time it adds a ban, so a long-running daemon never loses peers it
+/// has already discovered to a crash.
+pub struct RoutingTable {
+    self_id: PublicKey,
+    buckets: Vec<Bucket>,
+    path: PathBuf,
+}
+
+impl RoutingTable {
+    pub fn new(self_id: PublicKey, path: impl Into<PathBuf>) -> Self {
+        RoutingTable {
+            self_id,
+            buckets: (0..NUM_BUCKETS).map(|_| Bucket::default()).collect(),
+            path: path.into(),
+        }
+    }
+
+    pub fn self_id(&self) -> PublicKey {
+        self.self_id
+    }
+
+    /// Record that `id` is reachable at `addr` and persist the table. Never
+    /// inserts `self_id`.
+    pub fn insert(&mut self, id: PublicKey, addr: NetAddr<InetHost>) {
+        self.insert_without_persisting(id, addr);
+        let _ = self.persist();
+    }
+
+    /// As [`Self::insert`], but skips the save — used while [`Self::load`] is
+    /// replaying a table that's already on disk, so loading a table with `n`
+    /// entries doesn't re-write it `n` times.
+    fn insert_without_persisting(&mut self, id: PublicKey, addr: NetAddr<InetHost>) {
+        if id == self.self_id {
+            return;
+        }
+        if let Some(idx) = bucket_index(&self.self_id, &id) {
+            self.buckets[idx].seen(NodeEntry { id, addr });
+        }
+    }
+
+    /// The `k` entries closest to `target` by XOR distance, sorted nearest
+    /// first.
+    pub fn closest(&self, target: &PublicKey, k: usize) -> Vec<NodeEntry> {
+        let mut all: Vec<NodeEntry> = self
+            .buckets
+            .iter()
+            .flat_map(|b| b.entries.iter().cloned())
+            .collect();
+        all.sort_by_key(|entry| distance(target, &entry.id));
+        all.truncate(k);
+        all
+    }
+
+    /// Resolve a single known peer's address, e.g. for `Connect` to turn a
+    /// bare public key into something it can dial.
+    pub fn resolve(&self, id: &PublicKey) -> Option<NetAddr<InetHost>> {
+        let idx = bucket_index(&self.self_id, id)?;
+        self.buckets[idx]
+            .entries
+            .iter()
+            .find(|e| &e.id == id)
+            .map(|e| e.addr.clone())
+    }
+
+    /// Each full bucket's least-recently-seen entry, paired with its bucket
+    /// index so the caller can pass that index to [`Self::evict_stale`] once
+    /// it judges the entry unreachable.
+    pub fn stale_candidates(&self) -> Vec<(usize, NodeEntry)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.entries.len() >= K)
+            .filter_map(|(idx, b)| b.least_recently_seen().map(|e| (idx, e.clone())))
+            .collect()
+    }
+
+    pub fn evict_stale(&mut self, bucket_idx: usize) {
+        self.buckets[bucket_idx].evict_least_recently_seen();
+        let _ = self.persist();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.iter().all(|b| b.entries.is_empty())
+    }
+
+    /// Persist the table as `pubkey addr` lines, one per entry, mirroring
+    /// [`crate::reputation::ReputationTracker`]'s ban-list format.
+    fn persist(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut contents = String::new();
+        for bucket in &self.buckets {
+            for entry in &bucket.entries {
+                contents.push_str(&format!("{} {}\n", entry.id, entry.addr));
+            }
+        }
+        std::fs::write(&self.path, contents)
+    }
+
+    pub fn load(self_id: PublicKey, path: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut table = RoutingTable::new(self_id, path);
+        let contents = match std::fs::read_to_string(&table.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(table),
+            Err(err) => return Err(err),
+        };
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            let Some((id, addr)) = line.split_once(' ') else {
+                continue;
+            };
+            if let (Ok(id), Ok(addr)) =
+                (PublicKey::from_str(id), NetAddr::<InetHost>::from_str(addr))
+            {
+                table.insert_without_persisting(id, addr);
+            }
+        }
+        Ok(table)
+    }
+}
+
+pub fn default_table_path() -> PathBuf {
+    PathBuf::from(shellexpand::tilde("~/.nsh/peers").to_string())
+}
+
+/// The four message types multiplexed into [`crate::server::Delegate::discovery_input`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum DiscoveryMessage {
+    Ping,
+    Pong,
+    FindNode(PublicKey),
+    Nodes(Vec<NodeEntry>),
+}
+
+impl DiscoveryMessage {
+    const TAG_PING: u8 = 0;
+    const TAG_PONG: u8 = 1;
+    const TAG_FIND_NODE: u8 = 2;
+    const TAG_NODES: u8 = 3;
+
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            DiscoveryMessage::Ping => vec![Self::TAG_PING],
+            DiscoveryMessage::Pong => vec![Self::TAG_PONG],
+            DiscoveryMessage::FindNode(target) => {
+                let mut buf = vec![Self::TAG_FIND_NODE];
+                buf.extend_from_slice(&target.to_pk_compressed());
+                buf
+            }
+            DiscoveryMessage::Nodes(entries) => {
+                let mut buf = vec![Self::TAG_NODES, entries.len() as u8];
+                for entry in entries {
+                    let addr = entry.addr.to_string();
+                    buf.extend_from_slice(&entry.id.to_pk_compressed());
+                    buf.extend_from_slice(&(addr.len() as u16).to_be_bytes());
+                    buf.extend_from_slice(addr.as_bytes());
+                }
+                buf
+            }
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let err = || io::Error::new(io::ErrorKind::InvalidData, "malformed discovery message");
+        match buf.first().copied().ok_or_else(err)? {
+            Self::TAG_PING => Ok(DiscoveryMessage::Ping),
+            Self::TAG_PONG => Ok(DiscoveryMessage::Pong),
+            Self::TAG_FIND_NODE => {
+                let key_bytes = buf.get(1..33).ok_or_else(err)?;
+                let target = PublicKey::try_from(key_bytes).map_err(|_| err())?;
+                Ok(DiscoveryMessage::FindNode(target))
+            }
+            Self::TAG_NODES => {
+                let count = *buf.get(1).ok_or_else(err)? as usize;
+                let mut pos = 2;
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let key_bytes = buf.get(pos..pos + 32).ok_or_else(err)?;
+                    let id = PublicKey::try_from(key_bytes).map_err(|_| err())?;
+                    pos += 32;
+                    let addr_len = u16::from_be_bytes(
+                        buf.get(pos..pos + 2).ok_or_else(err)?.try_into().unwrap(),
+                    ) as usize;
+                    pos += 2;
+                    let addr_bytes = buf.get(pos..pos + addr_len).ok_or_else(err)?;
+                    let addr = std::str::from_utf8(addr_bytes)
+                        .map_err(|_| err())?
+                        .parse()
+                        .map_err(|_| err())?;
+                    pos += addr_len;
+                    entries.push(NodeEntry { id, addr });
+                }
+                Ok(DiscoveryMessage::Nodes(entries))
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(seed: u8) -> PublicKey {
+        PublicKey::try_from([seed; 32].as_slice()).unwrap()
+    }
+
+    fn addr(port: u16) -> NetAddr<InetHost> {
+        format!("127.0.0.1:{port}").parse().unwrap()
+    }
+
+    /// A path under the OS temp dir unique to this test process and call
+    /// site, so concurrent `cargo test` runs don't clobber each other's
+    /// routing table file.
+    fn scratch_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nsh-discovery-test-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn distance_is_zero_for_identical_keys() {
+        assert_eq!(distance(&key(1), &key(1)), [0u8; 32]);
+    }
+
+    #[test]
+    fn routing_table_inserts_and_resolves() {
+        let path = scratch_path("resolve");
+        let _ = std::fs::remove_file(&path);
+        let mut table = RoutingTable::new(key(1), path);
+        table.insert(key(2), addr(9000));
+        assert_eq!(table.resolve(&key(2)), Some(addr(9000)));
+        assert_eq!(table.resolve(&key(3)), None);
+    }
+
+    #[test]
+    fn routing_table_never_inserts_self() {
+        let path = scratch_path("self");
+        let _ = std::fs::remove_file(&path);
+        let mut table = RoutingTable::new(key(1), path);
+        table.insert(key(1), addr(9000));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn routing_table_closest_orders_by_xor_distance() {
+        let path = scratch_path("closest");
+        let _ = std::fs::remove_file(&path);
+        let mut table = RoutingTable::new(key(0), path);
+        table.insert(key(1), addr(9001));
+        table.insert(key(2), addr(9002));
+        table.insert(key(255), addr(9003));
+        let closest = table.closest(&key(0), 1);
+        assert_eq!(closest.len(), 1);
+        assert_eq!(closest[0].id, key(1));
+    }
+
+    #[test]
+    fn routing_table_save_and_load_round_trips() {
+        let path = scratch_path("persist");
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut table = RoutingTable::new(key(1), path.clone());
+            table.insert(key(2), addr(9100));
+        }
+        let loaded = RoutingTable::load(key(1), path.clone()).unwrap();
+        assert_eq!(loaded.resolve(&key(2)), Some(addr(9100)));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn routing_table_load_missing_file_is_empty() {
+        let path = scratch_path("missing");
+        let _ = std::fs::remove_file(&path);
+        let table = RoutingTable::load(key(1), path).unwrap();
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn discovery_message_round_trips() {
+        let entries = vec![
+            NodeEntry {
+                id: key(2),
+                addr: addr(9200),
+            },
+            NodeEntry {
+                id: key(3),
+                addr: addr(9300),
+            },
+        ];
+        for msg in [
+            DiscoveryMessage::Ping,
+            DiscoveryMessage::Pong,
+            DiscoveryMessage::FindNode(key(4)),
+            DiscoveryMessage::Nodes(entries),
+            DiscoveryMessage::Nodes(vec![]),
+        ] {
+            let decoded = DiscoveryMessage::decode(&msg.encode()).unwrap();
+            assert_eq!(decoded, msg);
+        }
+    }
+
+    #[test]
+    fn discovery_message_decode_rejects_truncated_find_node() {
+        assert!(DiscoveryMessage::decode(&[DiscoveryMessage::TAG_FIND_NODE, 1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn discovery_message_decode_rejects_unknown_tag() {
+        assert!(DiscoveryMessage::decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn discovery_message_decode_rejects_empty_buffer() {
+        assert!(DiscoveryMessage::decode(&[]).is_err());
+    }
+}
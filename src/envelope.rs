@@ -0,0 +1,66 @@
+//! Top-level tag multiplexing every protocol carried over an established
+//! [`crate::Session`]/[`crate::Transport`].
+//!
+//! A single connection accepted by [`crate::server::Server`] can carry the
+//! textual [`crate::command::Command`] handshake, a port-forward request and
+//! its [`crate::forward::ChannelFrame`]s, an interactive [`crate::pty`]
+//! session, or [`crate::discovery::DiscoveryMessage`] gossip — sometimes more
+//! than one of these over the same connection's lifetime. Forward requests,
+//! channel frames and discovery messages are prefixed with one of these tags
+//! so [`crate::server::Server`] can route them to the matching handler
+//! without having to guess from the shape of the bytes. A frame tagged
+//! `Command`, `PtyHandshake` or `Pty`, or one whose first byte is not a known
+//! tag (such as plain `Command` text), goes to
+//! [`crate::server::Delegate::input`].
+
+use std::io;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(u8)]
+pub enum FrameTag {
+    /// A [`crate::command::Command`], formatted via `Display`/parsed via
+    /// `FromStr`: the message every connection starts with.
+    Command = 0,
+    /// A [`crate::forward::ForwardSpec`] request, opening a port-forward.
+    Forward = 1,
+    /// A [`crate::forward::ChannelFrame`], multiplexed over an open forward.
+    Channel = 2,
+    /// A [`crate::pty::PtyHandshake`], opening an interactive shell.
+    PtyHandshake = 3,
+    /// A [`crate::pty::PtyFrame`], multiplexed over an open shell.
+    Pty = 4,
+    /// A [`crate::discovery::DiscoveryMessage`].
+    Discovery = 5,
+}
+
+impl FrameTag {
+    fn from_byte(byte: u8) -> Option<Self> {
+        Some(match byte {
+            0 => FrameTag::Command,
+            1 => FrameTag::Forward,
+            2 => FrameTag::Channel,
+            3 => FrameTag::PtyHandshake,
+            4 => FrameTag::Pty,
+            5 => FrameTag::Discovery,
+            _ => return None,
+        })
+    }
+}
+
+/// Prefix `body` with `tag`, ready to hand to `Action::Send`/`Write::write_all`.
+pub fn wrap(tag: FrameTag, body: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(1 + body.len());
+    buf.push(tag as u8);
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// Split a received frame into its tag and the bytes that follow it.
+pub fn unwrap(buf: &[u8]) -> io::Result<(FrameTag, &[u8])> {
+    let (&tag_byte, rest) = buf
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "empty frame"))?;
+    let tag = FrameTag::from_byte(tag_byte)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown frame tag"))?;
+    Ok((tag, rest))
+}
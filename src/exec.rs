@@ -0,0 +1,176 @@
+//! Tagged-frame protocol for [`crate::command::LocalCommand::Exec`].
+//!
+//! Unlike the one-shot `Echo`/`Date` commands, which just stream raw bytes
+//! back to the client, an arbitrary remote process has three independent
+//! output channels (stdout, stderr, and its exit status) that the client
+//! needs to keep separate rather than flattened into one byte stream.
+
+use std::io;
+
+/// Tag byte identifying which channel an [`ExecFrame`] carries.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(u8)]
+pub enum ExecTag {
+    Stdout = 1,
+    Stderr = 2,
+    ExitStatus = 3,
+}
+
+/// One frame of the remote process's output, as relayed by the server while
+/// the child is running (`Stdout`/`Stderr`) or once it has been reaped
+/// (`ExitStatus`).
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ExecFrame {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    ExitStatus(i32),
+}
+
+impl ExecFrame {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            ExecFrame::Stdout(chunk) => encode_chunk(ExecTag::Stdout, chunk),
+            ExecFrame::Stderr(chunk) => encode_chunk(ExecTag::Stderr, chunk),
+            ExecFrame::ExitStatus(code) => {
+                let mut buf = Vec::with_capacity(5);
+                buf.push(ExecTag::ExitStatus as u8);
+                buf.extend_from_slice(&code.to_be_bytes());
+                buf
+            }
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let err = || io::Error::new(io::ErrorKind::InvalidData, "malformed exec frame");
+        match buf.first().copied() {
+            Some(tag) if tag == ExecTag::Stdout as u8 => {
+                Ok(ExecFrame::Stdout(decode_chunk(&buf[1..])?))
+            }
+            Some(tag) if tag == ExecTag::Stderr as u8 => {
+                Ok(ExecFrame::Stderr(decode_chunk(&buf[1..])?))
+            }
+            Some(tag) if tag == ExecTag::ExitStatus as u8 => {
+                let bytes: [u8; 4] = buf.get(1..5).ok_or_else(err)?.try_into().unwrap();
+                Ok(ExecFrame::ExitStatus(i32::from_be_bytes(bytes)))
+            }
+            _ => Err(err()),
+        }
+    }
+
+    /// Decode one frame off the front of `buf`, returning it together with
+    /// how many bytes it consumed, or `None` if `buf` doesn't yet hold a
+    /// complete frame. Unlike [`Self::decode`], `buf` need not be exactly one
+    /// frame: the caller (e.g. the `nsh` binary's `run_exec`) accumulates whatever a
+    /// transport read hands back, which may coalesce several frames into one
+    /// read or split a single frame across several, and drains frames off the
+    /// front as they become complete.
+    pub fn try_decode(buf: &[u8]) -> io::Result<Option<(Self, usize)>> {
+        let err = || io::Error::new(io::ErrorKind::InvalidData, "malformed exec frame");
+        match buf.first().copied() {
+            Some(tag) if tag == ExecTag::Stdout as u8 => Ok(try_decode_chunk(&buf[1..])?
+                .map(|(chunk, consumed)| (ExecFrame::Stdout(chunk), 1 + consumed))),
+            Some(tag) if tag == ExecTag::Stderr as u8 => Ok(try_decode_chunk(&buf[1..])?
+                .map(|(chunk, consumed)| (ExecFrame::Stderr(chunk), 1 + consumed))),
+            Some(tag) if tag == ExecTag::ExitStatus as u8 => match buf.get(1..5) {
+                Some(bytes) => Ok(Some((
+                    ExecFrame::ExitStatus(i32::from_be_bytes(bytes.try_into().unwrap())),
+                    5,
+                ))),
+                None => Ok(None),
+            },
+            Some(_) => Err(err()),
+            None => Ok(None),
+        }
+    }
+}
+
+fn encode_chunk(tag: ExecTag, chunk: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(5 + chunk.len());
+    buf.push(tag as u8);
+    buf.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+    buf.extend_from_slice(chunk);
+    buf
+}
+
+fn decode_chunk(buf: &[u8]) -> io::Result<Vec<u8>> {
+    let err = || io::Error::new(io::ErrorKind::UnexpectedEof, "truncated exec chunk");
+    let len = u32::from_be_bytes(buf.get(0..4).ok_or_else(err)?.try_into().unwrap()) as usize;
+    Ok(buf.get(4..4 + len).ok_or_else(err)?.to_vec())
+}
+
+/// As [`decode_chunk`], but returns `None` instead of erroring when `buf`
+/// doesn't yet hold the chunk's length prefix or full body.
+fn try_decode_chunk(buf: &[u8]) -> io::Result<Option<(Vec<u8>, usize)>> {
+    if buf.len() < 4 {
+        return Ok(None);
+    }
+    let len = u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize;
+    Ok(buf.get(4..4 + len).map(|chunk| (chunk.to_vec(), 4 + len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec_frame_round_trips() {
+        for frame in [
+            ExecFrame::Stdout(vec![1, 2, 3]),
+            ExecFrame::Stderr(b"oops".to_vec()),
+            ExecFrame::Stdout(vec![]),
+            ExecFrame::ExitStatus(0),
+            ExecFrame::ExitStatus(-1),
+        ] {
+            let decoded = ExecFrame::decode(&frame.encode()).unwrap();
+            assert_eq!(decoded, frame);
+        }
+    }
+
+    #[test]
+    fn exec_frame_decode_rejects_truncated_chunk() {
+        let encoded = ExecFrame::Stdout(vec![1, 2, 3, 4]).encode();
+        assert!(ExecFrame::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn exec_frame_decode_rejects_truncated_exit_status() {
+        assert!(ExecFrame::decode(&[ExecTag::ExitStatus as u8, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn exec_frame_decode_rejects_unknown_tag() {
+        assert!(ExecFrame::decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn try_decode_consumes_exactly_one_frame_and_leaves_the_rest() {
+        let frame = ExecFrame::Stdout(vec![1, 2, 3]);
+        let mut buf = frame.encode();
+        buf.extend_from_slice(&ExecFrame::ExitStatus(0).encode());
+        let (decoded, consumed) = ExecFrame::try_decode(&buf).unwrap().unwrap();
+        assert_eq!(decoded, frame);
+        assert_eq!(consumed, frame.encode().len());
+        let (decoded, consumed) = ExecFrame::try_decode(&buf[consumed..]).unwrap().unwrap();
+        assert_eq!(decoded, ExecFrame::ExitStatus(0));
+        assert_eq!(consumed, buf.len() - frame.encode().len());
+    }
+
+    #[test]
+    fn try_decode_returns_none_on_a_split_frame() {
+        let encoded = ExecFrame::Stdout(vec![1, 2, 3, 4]).encode();
+        for split in 0..encoded.len() {
+            assert_eq!(ExecFrame::try_decode(&encoded[..split]).unwrap(), None);
+        }
+        assert!(ExecFrame::try_decode(&encoded).unwrap().is_some());
+    }
+
+    #[test]
+    fn try_decode_returns_none_on_empty_buffer() {
+        assert_eq!(ExecFrame::try_decode(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn try_decode_rejects_unknown_tag() {
+        assert!(ExecFrame::try_decode(&[0xff]).is_err());
+    }
+}
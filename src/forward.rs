@@ -0,0 +1,546 @@
+//! Port-forwarding configuration and the channel-multiplexing frame format
+//! used by [`crate::server::Delegate`] implementations that forward traffic
+//! across an established noise [`crate::Session`].
+//!
+//! A single session can carry many forwarded streams (or UDP flows)
+//! simultaneously, so every data frame carries a `u32` channel id naming the
+//! stream or flow it belongs to (see [`ChannelId`]).
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::time::Duration;
+
+use crate::envelope::{self, FrameTag};
+
+/// Which side opens the listening socket.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum ForwardDirection {
+    /// `ssh -L`: the local side listens and forwards to the remote peer.
+    LocalToRemote,
+    /// `ssh -R`: the remote side listens and forwards back to the local peer.
+    RemoteToLocal,
+}
+
+/// Transport carried by a single forward.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum ForwardProtocol {
+    Tcp,
+    Udp,
+}
+
+/// A forwarding request as parsed from the CLI: `direction`/`protocol`
+/// describe how the tunnel is wired, `bind` is the address the listening
+/// side binds, and `target` is the address the accepting side connects (or
+/// sends datagrams) to.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ForwardSpec {
+    pub direction: ForwardDirection,
+    pub protocol: ForwardProtocol,
+    pub bind: SocketAddr,
+    pub target: SocketAddr,
+}
+
+/// Identifier for one multiplexed forwarded stream or UDP flow, assigned by
+/// whichever side accepts the connection (or receives the first datagram)
+/// and echoed back on every subsequent frame for that channel.
+pub type ChannelId = u32;
+
+/// A frame multiplexed over the session on behalf of a [`ForwardSpec`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ChannelFrame {
+    /// Open a new forwarded TCP stream or UDP flow under `channel`.
+    Open { channel: ChannelId },
+    /// Bytes belonging to `channel`; for UDP this is one whole datagram.
+    Data {
+        channel: ChannelId,
+        payload: Vec<u8>,
+    },
+    /// A UDP datagram together with the peer address it came from (or
+    /// should be sent to, on the `RemoteToLocal` leg), since UDP has no
+    /// connection to demultiplex on.
+    Datagram {
+        channel: ChannelId,
+        addr: SocketAddr,
+        payload: Vec<u8>,
+    },
+    /// `channel` was closed on the sender's side.
+    Close { channel: ChannelId },
+}
+
+impl ForwardSpec {
+    /// Encode the control message a client sends right after the session is
+    /// established to ask the remote side to set up its half of the forward,
+    /// tagged with [`FrameTag::Forward`] so [`crate::server::Server`] can
+    /// tell it apart from an ordinary [`crate::command::Command`].
+    pub fn encode_request(&self) -> Vec<u8> {
+        envelope::wrap(FrameTag::Forward, &self.encode_request_body())
+    }
+
+    fn encode_request_body(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16);
+        buf.push(match self.direction {
+            ForwardDirection::LocalToRemote => 0,
+            ForwardDirection::RemoteToLocal => 1,
+        });
+        buf.push(match self.protocol {
+            ForwardProtocol::Tcp => 0,
+            ForwardProtocol::Udp => 1,
+        });
+        let bind = self.bind.to_string();
+        let target = self.target.to_string();
+        buf.extend_from_slice(&(bind.len() as u16).to_be_bytes());
+        buf.extend_from_slice(bind.as_bytes());
+        buf.extend_from_slice(&(target.len() as u16).to_be_bytes());
+        buf.extend_from_slice(target.as_bytes());
+        buf
+    }
+
+    pub fn decode_request(buf: &[u8]) -> io::Result<Self> {
+        let err = || io::Error::new(io::ErrorKind::InvalidData, "malformed forward request");
+        let (tag, buf) = envelope::unwrap(buf)?;
+        if tag != FrameTag::Forward {
+            return Err(err());
+        }
+        let direction = match buf.first().copied().ok_or_else(err)? {
+            0 => ForwardDirection::LocalToRemote,
+            1 => ForwardDirection::RemoteToLocal,
+            _ => return Err(err()),
+        };
+        let protocol = match buf.get(1).copied().ok_or_else(err)? {
+            0 => ForwardProtocol::Tcp,
+            1 => ForwardProtocol::Udp,
+            _ => return Err(err()),
+        };
+        let mut pos = 2;
+        let bind_len =
+            u16::from_be_bytes(buf.get(pos..pos + 2).ok_or_else(err)?.try_into().unwrap()) as usize;
+        pos += 2;
+        let bind = std::str::from_utf8(buf.get(pos..pos + bind_len).ok_or_else(err)?)
+            .map_err(|_| err())?
+            .parse()
+            .map_err(|_| err())?;
+        pos += bind_len;
+        let target_len =
+            u16::from_be_bytes(buf.get(pos..pos + 2).ok_or_else(err)?.try_into().unwrap()) as usize;
+        pos += 2;
+        let target = std::str::from_utf8(buf.get(pos..pos + target_len).ok_or_else(err)?)
+            .map_err(|_| err())?
+            .parse()
+            .map_err(|_| err())?;
+        Ok(ForwardSpec {
+            direction,
+            protocol,
+            bind,
+            target,
+        })
+    }
+}
+
+impl ChannelFrame {
+    const TAG_OPEN: u8 = 0;
+    const TAG_DATA: u8 = 1;
+    const TAG_DATAGRAM: u8 = 2;
+    const TAG_CLOSE: u8 = 3;
+
+    pub fn channel(&self) -> ChannelId {
+        match self {
+            ChannelFrame::Open { channel }
+            | ChannelFrame::Data { channel, .. }
+            | ChannelFrame::Datagram { channel, .. }
+            | ChannelFrame::Close { channel } => *channel,
+        }
+    }
+
+    /// Encode this frame as `[FrameTag::Channel][u32 body length][frame
+    /// body]`. The tag comes first so [`crate::server::Server::dispatch_data`]
+    /// can tell a `ChannelFrame` apart from any other tagged frame the same
+    /// way it does for every other [`FrameTag`], by reading byte zero; the
+    /// length prefix that follows covers the body only, so a reader pulling
+    /// frames off a raw byte stream (as [`relay_client`] does) can always
+    /// tell exactly how many more bytes make up one frame, rather than
+    /// guessing from a single `read()`'s worth of bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        let body = self.encode_body();
+        let mut length_and_body = Vec::with_capacity(4 + body.len());
+        length_and_body.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        length_and_body.extend_from_slice(&body);
+        envelope::wrap(FrameTag::Channel, &length_and_body)
+    }
+
+    fn encode_body(&self) -> Vec<u8> {
+        match self {
+            ChannelFrame::Open { channel } => {
+                let mut buf = vec![Self::TAG_OPEN];
+                buf.extend_from_slice(&channel.to_be_bytes());
+                buf
+            }
+            ChannelFrame::Data { channel, payload } => {
+                let mut buf = Vec::with_capacity(5 + payload.len());
+                buf.push(Self::TAG_DATA);
+                buf.extend_from_slice(&channel.to_be_bytes());
+                buf.extend_from_slice(payload);
+                buf
+            }
+            ChannelFrame::Datagram {
+                channel,
+                addr,
+                payload,
+            } => {
+                let addr = addr.to_string();
+                let mut buf = Vec::with_capacity(5 + 2 + addr.len() + payload.len());
+                buf.push(Self::TAG_DATAGRAM);
+                buf.extend_from_slice(&channel.to_be_bytes());
+                buf.extend_from_slice(&(addr.len() as u16).to_be_bytes());
+                buf.extend_from_slice(addr.as_bytes());
+                buf.extend_from_slice(payload);
+                buf
+            }
+            ChannelFrame::Close { channel } => {
+                let mut buf = vec![Self::TAG_CLOSE];
+                buf.extend_from_slice(&channel.to_be_bytes());
+                buf
+            }
+        }
+    }
+
+    /// Decode a complete, tagged and length-prefixed frame as produced by
+    /// [`Self::encode`].
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let err = || io::Error::new(io::ErrorKind::InvalidData, "malformed channel frame");
+        let (tag, rest) = envelope::unwrap(buf)?;
+        if tag != FrameTag::Channel {
+            return Err(err());
+        }
+        let len = u32::from_be_bytes(rest.get(0..4).ok_or_else(err)?.try_into().unwrap()) as usize;
+        let body = rest.get(4..4 + len).ok_or_else(err)?;
+        Self::decode_body(body)
+    }
+
+    fn decode_body(buf: &[u8]) -> io::Result<Self> {
+        let err = || io::Error::new(io::ErrorKind::InvalidData, "malformed channel frame");
+        let tag = *buf.first().ok_or_else(err)?;
+        let channel = u32::from_be_bytes(buf.get(1..5).ok_or_else(err)?.try_into().unwrap());
+        let rest = &buf[5..];
+        Ok(match tag {
+            Self::TAG_OPEN => ChannelFrame::Open { channel },
+            Self::TAG_DATA => ChannelFrame::Data {
+                channel,
+                payload: rest.to_vec(),
+            },
+            Self::TAG_DATAGRAM => {
+                let addr_len =
+                    u16::from_be_bytes(rest.get(0..2).ok_or_else(err)?.try_into().unwrap())
+                        as usize;
+                let addr_bytes = rest.get(2..2 + addr_len).ok_or_else(err)?;
+                let addr = std::str::from_utf8(addr_bytes)
+                    .map_err(|_| err())?
+                    .parse()
+                    .map_err(|_| err())?;
+                ChannelFrame::Datagram {
+                    channel,
+                    addr,
+                    payload: rest[2 + addr_len..].to_vec(),
+                }
+            }
+            Self::TAG_CLOSE => ChannelFrame::Close { channel },
+            _ => return Err(err()),
+        })
+    }
+}
+
+/// Client-side end of a non-`Tcp`/`LocalToRemote` forward: after the request
+/// has been sent, drive the bind side (listener or UDP socket) and relay
+/// `ChannelFrame`s over `session` until it disconnects.
+///
+/// For `LocalToRemote` forwards we own the listening socket and hand every
+/// accepted connection (or new UDP source address) a fresh [`ChannelId`]; for
+/// `RemoteToLocal` forwards the remote side owns the listener and we only
+/// ever dial `spec.target` locally when told to by an `Open`/`Datagram`
+/// frame.
+pub fn relay_client(session: &mut (impl Read + Write), spec: ForwardSpec) -> io::Result<()> {
+    match (spec.direction, spec.protocol) {
+        (ForwardDirection::LocalToRemote, ForwardProtocol::Tcp) => {
+            unreachable!("local TCP forwards are handled by netservices::tunnel::Tunnel")
+        }
+        (ForwardDirection::LocalToRemote, ForwardProtocol::Udp) => {
+            relay_local_udp(session, spec.bind, spec.target)
+        }
+        (ForwardDirection::RemoteToLocal, _) => relay_remote_to_local(session, spec.target),
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn write_frame(session: &mut (impl Read + Write), frame: &ChannelFrame) -> io::Result<()> {
+    session.write_all(&frame.encode())
+}
+
+fn relay_local_udp(
+    session: &mut (impl Read + Write),
+    bind: SocketAddr,
+    _target: SocketAddr,
+) -> io::Result<()> {
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+    let mut by_peer: HashMap<SocketAddr, ChannelId> = HashMap::new();
+    let mut next_channel: ChannelId = 1;
+    let mut buf = [0u8; 65_507];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((n, peer)) => {
+                let channel = *by_peer.entry(peer).or_insert_with(|| {
+                    let id = next_channel;
+                    next_channel += 1;
+                    id
+                });
+                let frame = ChannelFrame::Datagram {
+                    channel,
+                    addr: peer,
+                    payload: buf[..n].to_vec(),
+                };
+                write_frame(session, &frame)?;
+            }
+            Err(err) if is_timeout(&err) => {}
+            Err(err) => return Err(err),
+        }
+        // The reply path: the remote peer stamps the originating (our) peer
+        // address on every `Datagram` frame it sends back, so we don't need
+        // our own channel table to route the reply.
+        if let Some(frame) = try_read_frame(session)? {
+            if let ChannelFrame::Datagram { addr, payload, .. } = frame {
+                socket.send_to(&payload, addr)?;
+            }
+        }
+    }
+}
+
+/// Read one tagged, length-prefixed [`ChannelFrame`] off `session`, reading
+/// exactly as many bytes as its length prefix says so a frame split or
+/// coalesced across individual `read()`s is reassembled correctly. Treats a
+/// would-block/timeout on the leading tag byte as "nothing ready yet" rather
+/// than an error; once a frame has started arriving, the rest is read with
+/// blocking `read_exact` since a frame is never sent partially.
+fn try_read_frame(session: &mut (impl Read + Write)) -> io::Result<Option<ChannelFrame>> {
+    let mut tag_buf = [0u8; 1];
+    match session.read_exact(&mut tag_buf) {
+        Ok(()) => {}
+        Err(err) if is_timeout(&err) => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut len_buf = [0u8; 4];
+    session.read_exact(&mut len_buf)?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    let mut body = vec![0u8; len];
+    session.read_exact(&mut body)?;
+    let mut full = Vec::with_capacity(1 + 4 + len);
+    full.extend_from_slice(&tag_buf);
+    full.extend_from_slice(&len_buf);
+    full.extend_from_slice(&body);
+    ChannelFrame::decode(&full).map(Some)
+}
+
+/// Look up (or lazily open) the UDP socket relaying a `RemoteToLocal` flow's
+/// datagrams to/from `target`, keyed by [`ChannelId`] rather than address
+/// since the same channel may see several back-and-forth exchanges.
+fn udp_socket_for(
+    sockets: &mut HashMap<ChannelId, UdpSocket>,
+    channel: ChannelId,
+    target: SocketAddr,
+) -> io::Result<&UdpSocket> {
+    if let std::collections::hash_map::Entry::Vacant(entry) = sockets.entry(channel) {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        socket.connect(target)?;
+        entry.insert(socket);
+    }
+    Ok(&sockets[&channel])
+}
+
+/// Client-side half of a `RemoteToLocal` (`ssh -R`) forward: dial `target` locally on
+/// `Open`, pump bytes/datagrams in both directions, and tell the remote peer
+/// when a forwarded stream closes so it can tear down its own end.
+fn relay_remote_to_local(session: &mut (impl Read + Write), target: SocketAddr) -> io::Result<()> {
+    let mut streams: HashMap<ChannelId, TcpStream> = HashMap::new();
+    let mut sockets: HashMap<ChannelId, UdpSocket> = HashMap::new();
+    let mut reply_addrs: HashMap<ChannelId, SocketAddr> = HashMap::new();
+    let mut buf = [0u8; 65_507];
+
+    loop {
+        if let Some(frame) = try_read_frame(session)? {
+            match frame {
+                ChannelFrame::Open { channel } => {
+                    let stream = TcpStream::connect(target)?;
+                    stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+                    streams.insert(channel, stream);
+                }
+                ChannelFrame::Data { channel, payload } => {
+                    if let Some(stream) = streams.get_mut(&channel) {
+                        stream.write_all(&payload)?;
+                    }
+                }
+                ChannelFrame::Close { channel } => {
+                    streams.remove(&channel);
+                }
+                ChannelFrame::Datagram {
+                    channel,
+                    addr,
+                    payload,
+                } => {
+                    udp_socket_for(&mut sockets, channel, target)?.send(&payload)?;
+                    reply_addrs.insert(channel, addr);
+                }
+            }
+        }
+
+        let mut closed = Vec::new();
+        for (&channel, stream) in streams.iter_mut() {
+            match stream.read(&mut buf) {
+                Ok(0) => closed.push(channel),
+                Ok(n) => write_frame(
+                    session,
+                    &ChannelFrame::Data {
+                        channel,
+                        payload: buf[..n].to_vec(),
+                    },
+                )?,
+                Err(err) if is_timeout(&err) => {}
+                Err(_) => closed.push(channel),
+            }
+        }
+        for channel in closed {
+            streams.remove(&channel);
+            write_frame(session, &ChannelFrame::Close { channel })?;
+        }
+
+        for (&channel, socket) in sockets.iter() {
+            match socket.recv(&mut buf) {
+                Ok(n) => {
+                    if let Some(&addr) = reply_addrs.get(&channel) {
+                        write_frame(
+                            session,
+                            &ChannelFrame::Datagram {
+                                channel,
+                                addr,
+                                payload: buf[..n].to_vec(),
+                            },
+                        )?;
+                    }
+                }
+                Err(err) if is_timeout(&err) => {}
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+/// Bind a fresh listener for a `RemoteToLocal` TCP forward requested by a
+/// peer, i.e. the server-side half of `-R`.
+pub fn bind_reverse_listener(bind: SocketAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(bind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_request_round_trips() {
+        let spec = ForwardSpec {
+            direction: ForwardDirection::RemoteToLocal,
+            protocol: ForwardProtocol::Udp,
+            bind: "127.0.0.1:9000".parse().unwrap(),
+            target: "10.0.0.1:53".parse().unwrap(),
+        };
+        let decoded = ForwardSpec::decode_request(&spec.encode_request()).unwrap();
+        assert_eq!(decoded, spec);
+    }
+
+    #[test]
+    fn forward_request_decode_rejects_wrong_tag() {
+        let body = envelope::wrap(FrameTag::Channel, &[0, 0]);
+        assert!(ForwardSpec::decode_request(&body).is_err());
+    }
+
+    #[test]
+    fn channel_frame_round_trips() {
+        for frame in [
+            ChannelFrame::Open { channel: 1 },
+            ChannelFrame::Data {
+                channel: 2,
+                payload: vec![1, 2, 3],
+            },
+            ChannelFrame::Datagram {
+                channel: 3,
+                addr: "127.0.0.1:4242".parse().unwrap(),
+                payload: vec![4, 5, 6],
+            },
+            ChannelFrame::Close { channel: 4 },
+        ] {
+            let decoded = ChannelFrame::decode(&frame.encode()).unwrap();
+            assert_eq!(decoded, frame);
+        }
+    }
+
+    #[test]
+    fn channel_frame_decode_rejects_short_length_prefix() {
+        assert!(ChannelFrame::decode(&[FrameTag::Channel as u8, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn channel_frame_decode_rejects_wrong_tag() {
+        let body = envelope::wrap(FrameTag::Forward, &[0, 0, 0, 0]);
+        assert!(ChannelFrame::decode(&body).is_err());
+    }
+
+    #[test]
+    fn channel_frame_decode_rejects_truncated_body() {
+        let mut encoded = ChannelFrame::Open { channel: 7 }.encode();
+        encoded.truncate(encoded.len() - 1);
+        assert!(ChannelFrame::decode(&encoded).is_err());
+    }
+
+    /// A read-only in-memory stream that also implements `Write` (discarding
+    /// everything written), so it satisfies `try_read_frame`'s `Read + Write`
+    /// bound without needing a real socket pair.
+    struct ReadOnly(io::Cursor<Vec<u8>>);
+
+    impl Read for ReadOnly {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for ReadOnly {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn try_read_frame_reads_one_length_prefixed_frame() {
+        let frame = ChannelFrame::Data {
+            channel: 9,
+            payload: vec![10, 20, 30],
+        };
+        let mut stream = ReadOnly(io::Cursor::new(frame.encode()));
+        let decoded = try_read_frame(&mut stream).unwrap().unwrap();
+        assert_eq!(decoded, frame);
+    }
+
+    #[test]
+    fn try_read_frame_errors_on_empty_stream() {
+        let mut stream = ReadOnly(io::Cursor::new(Vec::new()));
+        assert!(try_read_frame(&mut stream).is_err());
+    }
+}
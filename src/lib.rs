@@ -7,6 +7,13 @@ use netservices::noise::NoiseXk;
 use std::net;
 
 pub mod client;
+pub mod discovery;
+pub mod envelope;
+pub mod exec;
+pub mod forward;
+pub mod listen;
+pub mod pty;
+pub mod reputation;
 pub mod service;
 pub mod shell;
 
@@ -0,0 +1,46 @@
+//! Listen-address abstraction covering both TCP sockets and Unix domain
+//! sockets, so a daemon can be exposed either over the network or behind a
+//! filesystem path with its own permissions.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use cyphernet::addr::{InetHost, NetAddr};
+
+/// Where a `nsh` daemon listens: either a regular `NetAddr` (as parsed
+/// everywhere else in this crate) or a Unix domain socket path, written as
+/// `unix:<path>` on the command line.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ListenAddr {
+    Net(NetAddr<InetHost>),
+    Unix(PathBuf),
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Net(addr) => write!(f, "{addr}"),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug, Display, Error)]
+#[display("invalid listen address '{0}'")]
+pub struct InvalidListenAddr(String);
+
+impl FromStr for ListenAddr {
+    type Err = InvalidListenAddr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            Some(_) => Err(InvalidListenAddr(s.to_owned())),
+            None => s
+                .parse()
+                .map(ListenAddr::Net)
+                .map_err(|_| InvalidListenAddr(s.to_owned())),
+        }
+    }
+}
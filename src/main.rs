@@ -5,6 +5,8 @@ use std::any::Any;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{fs, io, thread};
 
@@ -14,10 +16,15 @@ use cyphernet::{ed25519, Cert, Digest, EcPk, EcSign, EcSk, Sha256};
 use netservices::tunnel::Tunnel;
 use netservices::NetSession;
 use nsh::client::Client;
-use nsh::command::Command;
+use nsh::command::{Command, LocalCommand};
+use nsh::discovery::{self, RoutingTable};
+use nsh::exec::ExecFrame;
+use nsh::forward::{self, ForwardDirection, ForwardProtocol, ForwardSpec};
+use nsh::listen::ListenAddr;
 use nsh::processor::Processor;
+use nsh::pty::{PtyFrame, PtyHandshake, WindowSize};
 use nsh::server::{Accept, Server};
-use nsh::shell::LogLevel;
+use nsh::shell::{self, LogLevel};
 use nsh::{RemoteHost, Session, Transport};
 use reactor::poller::popol;
 use reactor::Reactor;
@@ -37,11 +44,13 @@ struct Args {
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
 
-    /// Start as a daemon listening on a specific socket
+    /// Start as a daemon listening on a specific socket or Unix domain socket
     ///
-    /// If the socket address is not given, defaults to 127.0.0.1:3232
+    /// Accepts either a socket address (defaulting to 127.0.0.1:3232 if none
+    /// is given) or `unix:<path>` to listen on a Unix domain socket instead,
+    /// e.g. `--listen unix:/run/nsh.sock`.
     #[arg(short, long)]
-    pub listen: Option<Option<PartialAddr<InetHost, DEFAULT_PORT>>>,
+    pub listen: Option<Option<String>>,
 
     /// Path to an identity (key) file
     #[arg(short, long, require_equals = true)]
@@ -62,6 +71,32 @@ struct Args {
     #[arg(short, long, conflicts_with = "listen")]
     pub tunnel: Option<Option<PartialAddr<InetHost, DEFAULT_SOCKS5_PORT>>>,
 
+    /// Reverse the direction of `--tunnel`
+    ///
+    /// Instead of listening locally, asks the remote host to listen on
+    /// `--tunnel`'s address and forward accepted connections back to this
+    /// side, analogous to `ssh -R`.
+    #[arg(short = 'R', long, requires = "tunnel")]
+    pub reverse: bool,
+
+    /// Forward UDP datagrams instead of TCP streams
+    ///
+    /// Only meaningful together with `--tunnel`; each datagram is framed
+    /// individually since UDP has no connection to demultiplex on.
+    #[arg(long, requires = "tunnel")]
+    pub udp: bool,
+
+    /// Destination address to forward accepted connections (or datagrams) to
+    ///
+    /// Analogous to the `host:hostport` half of `ssh -R bind:port:host:hostport`:
+    /// for `-R`, this is a *local* address dialed on this side whenever the
+    /// remote peer accepts a connection; for `--udp` without `-R`, it's the
+    /// address the remote peer relays datagrams to on its side. Required with
+    /// `-R` and/or `--udp`; has no effect on a plain `--tunnel` (local TCP
+    /// forward), which dials through `REMOTE_HOST` itself.
+    #[arg(long, requires = "tunnel", require_equals = true)]
+    pub forward_to: Option<PartialAddr<InetHost, DEFAULT_PORT>>,
+
     /// Address of the remote host to connect
     ///
     /// Remote address, if no proxy is used, should be either IPv4 or IPv6
@@ -71,9 +106,14 @@ struct Args {
     /// Nym address.
     ///
     /// If the address is provided without a port, a default port 3232 is used.
-    #[arg(conflicts_with = "listen", required_unless_present = "listen")]
+    #[arg(conflicts_with_all = ["listen", "resolve"], required_unless_present_any = ["listen", "resolve"])]
     pub remote_host: Option<PeerAddr<ed25519::PublicKey, AddrArg>>,
 
+    /// Resolve a bare public key to a reachable address via this node's
+    /// discovery routing table instead of requiring a socket address
+    #[arg(long, conflicts_with_all = ["listen", "remote_host"], require_equals = true)]
+    pub resolve: Option<ed25519::PublicKey>,
+
     /// Connection timeout duration, in seconds
     #[arg(short = 'T', long, default_value = "10", require_equals = true)]
     pub timeout: u8,
@@ -84,10 +124,16 @@ struct Args {
 }
 
 enum Mode {
-    Listen(NetAddr<InetHost>),
+    Listen(ListenAddr),
     Tunnel {
         local: NetAddr<InetHost>,
         remote: RemoteHost,
+        direction: ForwardDirection,
+        protocol: ForwardProtocol,
+        /// The `--forward-to` destination; `None` only for a plain
+        /// `LocalToRemote`/`Tcp` forward, which `netservices::tunnel::Tunnel`
+        /// relays without needing a separate target address.
+        target: Option<NetAddr<InetHost>>,
     },
     Connect {
         host: RemoteHost,
@@ -147,27 +193,7 @@ impl TryFrom<Args> for Config {
     type Error = AppError;
 
     fn try_from(args: Args) -> Result<Self, Self::Error> {
-        let command = if let Some(listen) = args.listen {
-            let local_socket = listen.unwrap_or_else(Localhost::localhost).into();
-            Mode::Listen(local_socket)
-        } else if let Some(tunnel) = args.tunnel {
-            let local = tunnel
-                .unwrap_or_else(|| PartialAddr::localhost(None))
-                .into();
-            let remote = args.remote_host.expect("clap library broken");
-            Mode::Tunnel {
-                local,
-                remote: remote.into(),
-            }
-        } else {
-            let host = args.remote_host.expect("clap library broken");
-            Mode::Connect {
-                host: host.into(),
-                command: args.command.unwrap_or(Command::DATE),
-            }
-        };
-
-        let id_path = args.id.unwrap_or_else(|| {
+        let id_path = args.id.clone().unwrap_or_else(|| {
             let mut dir = PathBuf::from(DEFAULT_DIR);
             dir.push(DEFAULT_ID_FILE);
             dir
@@ -194,6 +220,74 @@ impl TryFrom<Args> for Config {
         let node_keys = NodeKeys::from(id_priv);
         println!("Using identity {}", node_keys.pk());
 
+        let command = if let Some(listen) = args.listen {
+            let listen_addr = match listen {
+                None => {
+                    ListenAddr::Net(PartialAddr::<InetHost, DEFAULT_PORT>::localhost(None).into())
+                }
+                Some(s) if s.starts_with("unix:") => s.parse().map_err(|_| {
+                    AppError::Io(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid listen address '{s}'"),
+                    ))
+                })?,
+                Some(s) => {
+                    let addr: PartialAddr<InetHost, DEFAULT_PORT> = s.parse().map_err(|_| {
+                        AppError::Io(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("invalid listen address '{s}'"),
+                        ))
+                    })?;
+                    ListenAddr::Net(addr.into())
+                }
+            };
+            Mode::Listen(listen_addr)
+        } else if let Some(tunnel) = args.tunnel {
+            let local = tunnel
+                .unwrap_or_else(|| PartialAddr::localhost(None))
+                .into();
+            let remote = args.remote_host.expect("clap library broken");
+            let direction = if args.reverse {
+                ForwardDirection::RemoteToLocal
+            } else {
+                ForwardDirection::LocalToRemote
+            };
+            let protocol = if args.udp {
+                ForwardProtocol::Udp
+            } else {
+                ForwardProtocol::Tcp
+            };
+            let target = match args.forward_to {
+                Some(addr) => Some(addr.into()),
+                None if args.reverse || args.udp => {
+                    return Err(AppError::Io(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "--tunnel -R/--udp requires --forward-to=<host:port>, the destination to forward accepted connections to",
+                    )))
+                }
+                None => None,
+            };
+            Mode::Tunnel {
+                local,
+                remote: remote.into(),
+                direction,
+                protocol,
+                target,
+            }
+        } else {
+            let host = match args.remote_host {
+                Some(host) => host.into(),
+                None => {
+                    let target = args.resolve.expect("clap library broken");
+                    resolve_via_discovery(node_keys.pk().clone(), target)?
+                }
+            };
+            Mode::Connect {
+                host,
+                command: args.command.unwrap_or(Command::DATE),
+            }
+        };
+
         let force_proxy = args.proxy.is_some();
         let proxy_addr = args
             .proxy
@@ -211,7 +305,24 @@ impl TryFrom<Args> for Config {
     }
 }
 
-fn run() -> Result<(), AppError> {
+/// Turn a bare public key into a dialable [`RemoteHost`] using this node's
+/// persisted Kademlia [`RoutingTable`] (see `nsh::discovery`), populated by
+/// whatever daemon previously ran discovery lookups from this identity.
+fn resolve_via_discovery(
+    self_id: ed25519::PublicKey,
+    target: ed25519::PublicKey,
+) -> Result<RemoteHost, AppError> {
+    let table = RoutingTable::load(self_id, discovery::default_table_path())?;
+    let addr = table.resolve(&target).ok_or_else(|| {
+        AppError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no known address for {target}; no discovery lookup has seen it yet"),
+        ))
+    })?;
+    Ok(PeerAddr::new(target, addr).into())
+}
+
+fn run() -> Result<ExitCode, AppError> {
     let args = Args::parse();
 
     LogLevel::from_verbosity_flag_count(args.verbose).apply();
@@ -219,8 +330,8 @@ fn run() -> Result<(), AppError> {
     let config = Config::try_from(args)?;
 
     match config.mode {
-        Mode::Listen(socket_addr) => {
-            println!("Listening on {socket_addr} ...");
+        Mode::Listen(listen_addr) => {
+            println!("Listening on {listen_addr} ...");
 
             let processor = Processor::with(
                 config.node_keys.cert,
@@ -229,7 +340,7 @@ fn run() -> Result<(), AppError> {
                 config.force_proxy,
                 config.timeout,
             );
-            let service = Server::with(&socket_addr, processor)?;
+            let service = Server::with(&listen_addr, processor, config.node_keys.pk().clone())?;
             let reactor = Reactor::with(
                 service,
                 popol::Poller::new(),
@@ -238,7 +349,13 @@ fn run() -> Result<(), AppError> {
 
             reactor.join()?;
         }
-        Mode::Tunnel { remote, local } => {
+        Mode::Tunnel {
+            remote,
+            local,
+            direction: ForwardDirection::LocalToRemote,
+            protocol: ForwardProtocol::Tcp,
+            target: _,
+        } => {
             eprintln!("Tunneling to {remote} from {local}...");
 
             let session = Session::connect_blocking::<{ Sha256::OUTPUT_LEN }>(
@@ -260,6 +377,66 @@ fn run() -> Result<(), AppError> {
             let _ = tunnel.tunnel_once(popol::Poller::new(), Duration::from_secs(10))?;
             tunnel.into_session().disconnect()?;
         }
+        Mode::Tunnel {
+            remote,
+            local,
+            direction,
+            protocol,
+            target,
+        } => {
+            // Remote (`-R`) and/or UDP forwards have no `netservices::tunnel`
+            // support, so we speak the `ForwardSpec`/`ChannelFrame` protocol
+            // directly over the session instead of handing it to `Tunnel`.
+            eprintln!("Forwarding ({direction}/{protocol}) {local} <-> {remote}...");
+
+            // `Config::try_from` only ever leaves `target` unset for the
+            // `LocalToRemote`/`Tcp` case handled by the arm above, so every
+            // `ForwardSpec` constructed here has a real `--forward-to`
+            // destination behind it.
+            let target = target.expect("ForwardSpec target required outside LocalToRemote/Tcp");
+
+            let spec = ForwardSpec {
+                direction,
+                protocol,
+                bind: match local {
+                    NetAddr::Inet(addr) => addr.into(),
+                    _ => {
+                        return Err(AppError::Tunnel(
+                            remote,
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "forward requires an IP bind address",
+                            ),
+                        ))
+                    }
+                },
+                target: match target {
+                    NetAddr::Inet(addr) => addr.into(),
+                    _ => {
+                        return Err(AppError::Tunnel(
+                            remote,
+                            io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "forward requires an IP --forward-to address",
+                            ),
+                        ))
+                    }
+                },
+            };
+
+            let mut session = Session::connect_blocking::<{ Sha256::OUTPUT_LEN }>(
+                remote.addr.clone(),
+                config.node_keys.cert,
+                vec![remote.id],
+                config.node_keys.sk.clone(),
+                config.proxy_addr.clone(),
+                config.force_proxy,
+                config.timeout,
+            )?;
+            session.write_all(&spec.encode_request())?;
+            forward::relay_client(&mut session, spec)?;
+            session.disconnect()?;
+        }
         Mode::Connect { host, command } => {
             eprint!("Connecting to {host} ");
             if config.force_proxy {
@@ -267,8 +444,6 @@ fn run() -> Result<(), AppError> {
             }
             eprintln!("...");
 
-            let mut stdout = io::stdout();
-
             let mut client = Client::connect(
                 host,
                 config.node_keys.cert,
@@ -277,25 +452,146 @@ fn run() -> Result<(), AppError> {
                 config.force_proxy,
                 config.timeout,
             )?;
-            let mut printout = client.exec(command)?;
-            eprintln!("Remote output >>>");
-            for batch in &mut printout {
-                stdout.write_all(&batch)?;
-            }
-            stdout.flush()?;
-            client = printout.complete();
-            client.disconnect()?;
 
-            eprintln!("<<< done");
+            match &command {
+                Command::Execute {
+                    command: LocalCommand::Shell,
+                } => run_shell(client, command)?,
+                Command::Execute {
+                    command: LocalCommand::Exec { .. },
+                } => return Ok(run_exec(client, command)?),
+                _ => {
+                    let mut stdout = io::stdout();
+                    let mut printout = client.exec(command)?;
+                    eprintln!("Remote output >>>");
+                    for batch in &mut printout {
+                        stdout.write_all(&batch)?;
+                    }
+                    stdout.flush()?;
+                    client = printout.complete();
+                    client.disconnect()?;
+
+                    eprintln!("<<< done");
+                }
+            }
         }
     }
 
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Query the local tty for its current dimensions via `TIOCGWINSZ`.
+fn local_winsize() -> WindowSize {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) };
+    if ret != 0 {
+        return WindowSize::default();
+    }
+    WindowSize {
+        rows: ws.ws_row,
+        cols: ws.ws_col,
+        xpixel: ws.ws_xpixel,
+        ypixel: ws.ws_ypixel,
+    }
+}
+
+/// Negotiate an interactive shell: send the `$TERM`/terminfo/window-size
+/// handshake, put the local terminal into raw mode for the duration of the
+/// session, and pump [`PtyFrame`]s between the local tty and the remote PTY
+/// until the server reports the child has exited.
+fn run_shell(mut client: Client, command: Command) -> Result<(), AppError> {
+    let term = std::env::var("TERM").unwrap_or_else(|_| s!("xterm"));
+    // `term`'s first directory component under terminfo's hashed layout is
+    // its first character, not its first byte, so this must not slice by
+    // byte index: an attacker-controlled `$TERM` starting with a multi-byte
+    // UTF-8 character would otherwise panic here.
+    let first_char = term.chars().next().map(String::from).unwrap_or_default();
+    let terminfo = fs::read(format!("/usr/share/terminfo/{first_char}/{term}")).unwrap_or_default();
+    let handshake = PtyHandshake {
+        term,
+        terminfo,
+        winsize: local_winsize(),
+    };
+
+    let raw = shell::RawMode::enable()?;
+    let resized = Arc::new(AtomicBool::new(false));
+    shell::install_sigwinch_handler(Arc::clone(&resized));
+
+    let mut printout = client.exec_pty(command, handshake)?;
+    let mut stdout = io::stdout();
+    // Drive the resize-flag check and `printout`'s frame reads from the same
+    // loop iteration, rather than draining `printout` with an inner `for`
+    // loop that only hands control back here once the connection itself has
+    // nothing left to read. A SIGWINCH is now acted on as soon as the frame
+    // in flight when it arrived is delivered, instead of only after the
+    // whole session's remaining output has drained.
+    let exit_code = 'session: loop {
+        if resized.swap(false, Ordering::Relaxed) {
+            printout.send(PtyFrame::Resize(local_winsize()))?;
+        }
+        match printout.next() {
+            Some(PtyFrame::Data(data)) => stdout.write_all(&data)?,
+            Some(PtyFrame::Exit(status)) => break 'session status,
+            Some(PtyFrame::Resize(_)) => {}
+            None => break 'session 0,
+        }
+        stdout.flush()?;
+    };
+    drop(raw);
+
+    client = printout.complete();
+    client.disconnect()?;
+    if exit_code != 0 {
+        eprintln!("shell exited with status {exit_code}");
+    }
     Ok(())
 }
 
+/// Run a [`LocalCommand::Exec`], splitting the server's tagged frames back
+/// into `stdout`/`stderr` and propagating the remote exit code as our own.
+fn run_exec(mut client: Client, command: Command) -> Result<ExitCode, AppError> {
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
+    let mut exit_code = 0i32;
+
+    let mut printout = client.exec(command)?;
+    // Each `batch` is just whatever one transport read handed back, not
+    // necessarily one whole `ExecFrame`, so frames are reassembled from a
+    // running buffer rather than decoded one-per-batch.
+    let mut buf = Vec::new();
+    for batch in &mut printout {
+        buf.extend_from_slice(&batch);
+        let mut pos = 0;
+        while let Some((frame, consumed)) = ExecFrame::try_decode(&buf[pos..])? {
+            match frame {
+                ExecFrame::Stdout(chunk) => stdout.write_all(&chunk)?,
+                ExecFrame::Stderr(chunk) => stderr.write_all(&chunk)?,
+                ExecFrame::ExitStatus(status) => exit_code = status,
+            }
+            pos += consumed;
+        }
+        buf.drain(..pos);
+    }
+    stdout.flush()?;
+    stderr.flush()?;
+
+    client = printout.complete();
+    client.disconnect()?;
+
+    // Only the low byte of a process exit status is ever observable to our
+    // own caller (the same truncation `std::process::exit` itself applies on
+    // unix), so that's what we propagate here rather than collapsing every
+    // nonzero/negative/signal status to a single flat `1`.
+    Ok(if exit_code == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(exit_code as u8)
+    })
+}
+
 fn main() -> ExitCode {
     match run() {
-        Ok(_) => ExitCode::SUCCESS,
+        Ok(code) => code,
         Err(err) => {
             eprintln!("Error: {}", err);
             ExitCode::FAILURE
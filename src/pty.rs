@@ -0,0 +1,220 @@
+//! Framing for interactive PTY sessions carried in-band over a [`crate::Session`].
+//!
+//! A `Shell` command multiplexes three things over the single encrypted
+//! stream: raw terminal data, live window-resize notifications and the final
+//! exit status. Every frame starts with a one-byte tag so the two ends agree
+//! on how to interpret what follows without needing a second channel.
+
+use std::io;
+
+/// Terminal dimensions as reported by `TIOCGWINSZ`/sent on `SIGWINCH`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub cols: u16,
+    pub xpixel: u16,
+    pub ypixel: u16,
+}
+
+impl WindowSize {
+    pub fn encode(&self) -> [u8; 8] {
+        let mut buf = [0u8; 8];
+        buf[0..2].copy_from_slice(&self.rows.to_be_bytes());
+        buf[2..4].copy_from_slice(&self.cols.to_be_bytes());
+        buf[4..6].copy_from_slice(&self.xpixel.to_be_bytes());
+        buf[6..8].copy_from_slice(&self.ypixel.to_be_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 8 {
+            return None;
+        }
+        Some(WindowSize {
+            rows: u16::from_be_bytes([buf[0], buf[1]]),
+            cols: u16::from_be_bytes([buf[2], buf[3]]),
+            xpixel: u16::from_be_bytes([buf[4], buf[5]]),
+            ypixel: u16::from_be_bytes([buf[6], buf[7]]),
+        })
+    }
+}
+
+/// Handshake sent by the client immediately after requesting
+/// [`crate::command::LocalCommand::Shell`], before any [`PtyFrame`] flows.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PtyHandshake {
+    pub term: String,
+    pub terminfo: Vec<u8>,
+    pub winsize: WindowSize,
+}
+
+impl PtyHandshake {
+    pub fn encode(&self) -> Vec<u8> {
+        let term = self.term.as_bytes();
+        let mut buf = Vec::with_capacity(2 + term.len() + 4 + self.terminfo.len() + 8);
+        buf.extend_from_slice(&(term.len() as u16).to_be_bytes());
+        buf.extend_from_slice(term);
+        buf.extend_from_slice(&(self.terminfo.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&self.terminfo);
+        buf.extend_from_slice(&self.winsize.encode());
+        buf
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let err = || io::Error::new(io::ErrorKind::UnexpectedEof, "truncated PTY handshake");
+        if buf.len() < 2 {
+            return Err(err());
+        }
+        let term_len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+        let mut pos = 2;
+        let term = buf
+            .get(pos..pos + term_len)
+            .ok_or_else(err)
+            .map(|s| String::from_utf8_lossy(s).into_owned())?;
+        pos += term_len;
+        let terminfo_len =
+            u32::from_be_bytes(buf.get(pos..pos + 4).ok_or_else(err)?.try_into().unwrap()) as usize;
+        pos += 4;
+        let terminfo = buf.get(pos..pos + terminfo_len).ok_or_else(err)?.to_vec();
+        pos += terminfo_len;
+        let winsize = WindowSize::decode(buf.get(pos..).ok_or_else(err)?).ok_or_else(err)?;
+        Ok(PtyHandshake {
+            term,
+            terminfo,
+            winsize,
+        })
+    }
+}
+
+/// Tag byte identifying the kind of frame multiplexed over the PTY channel.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(u8)]
+pub enum PtyTag {
+    Data = 0,
+    Resize = 1,
+    Exit = 2,
+}
+
+/// A single multiplexed PTY frame, as sent in both directions once the
+/// session is established.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum PtyFrame {
+    Data(Vec<u8>),
+    Resize(WindowSize),
+    Exit(i32),
+}
+
+impl PtyFrame {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            PtyFrame::Data(data) => {
+                let mut buf = Vec::with_capacity(1 + data.len());
+                buf.push(PtyTag::Data as u8);
+                buf.extend_from_slice(data);
+                buf
+            }
+            PtyFrame::Resize(winsize) => {
+                let mut buf = Vec::with_capacity(9);
+                buf.push(PtyTag::Resize as u8);
+                buf.extend_from_slice(&winsize.encode());
+                buf
+            }
+            PtyFrame::Exit(status) => {
+                let mut buf = Vec::with_capacity(5);
+                buf.push(PtyTag::Exit as u8);
+                buf.extend_from_slice(&status.to_be_bytes());
+                buf
+            }
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let err = || io::Error::new(io::ErrorKind::InvalidData, "malformed PTY frame");
+        match buf.first().copied() {
+            Some(tag) if tag == PtyTag::Data as u8 => Ok(PtyFrame::Data(buf[1..].to_vec())),
+            Some(tag) if tag == PtyTag::Resize as u8 => Ok(PtyFrame::Resize(
+                WindowSize::decode(&buf[1..]).ok_or_else(err)?,
+            )),
+            Some(tag) if tag == PtyTag::Exit as u8 => {
+                let bytes: [u8; 4] = buf.get(1..5).ok_or_else(err)?.try_into().unwrap();
+                Ok(PtyFrame::Exit(i32::from_be_bytes(bytes)))
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_size_round_trips() {
+        let ws = WindowSize {
+            rows: 24,
+            cols: 80,
+            xpixel: 640,
+            ypixel: 480,
+        };
+        assert_eq!(WindowSize::decode(&ws.encode()), Some(ws));
+    }
+
+    #[test]
+    fn window_size_decode_rejects_short_buffer() {
+        assert_eq!(WindowSize::decode(&[0u8; 7]), None);
+    }
+
+    #[test]
+    fn pty_handshake_round_trips() {
+        let handshake = PtyHandshake {
+            term: s!("xterm-256color"),
+            terminfo: vec![1, 2, 3, 4, 5],
+            winsize: WindowSize {
+                rows: 40,
+                cols: 120,
+                xpixel: 0,
+                ypixel: 0,
+            },
+        };
+        let decoded = PtyHandshake::decode(&handshake.encode()).unwrap();
+        assert_eq!(decoded, handshake);
+    }
+
+    #[test]
+    fn pty_handshake_decode_rejects_truncated_buffer() {
+        let handshake = PtyHandshake {
+            term: s!("xterm"),
+            terminfo: vec![9; 16],
+            winsize: WindowSize::default(),
+        };
+        let encoded = handshake.encode();
+        assert!(PtyHandshake::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn pty_frame_round_trips() {
+        for frame in [
+            PtyFrame::Data(vec![1, 2, 3]),
+            PtyFrame::Resize(WindowSize {
+                rows: 50,
+                cols: 200,
+                xpixel: 1,
+                ypixel: 2,
+            }),
+            PtyFrame::Exit(-1),
+        ] {
+            let decoded = PtyFrame::decode(&frame.encode()).unwrap();
+            assert_eq!(decoded, frame);
+        }
+    }
+
+    #[test]
+    fn pty_frame_decode_rejects_unknown_tag() {
+        assert!(PtyFrame::decode(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn pty_frame_decode_rejects_empty_buffer() {
+        assert!(PtyFrame::decode(&[]).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+//! Peer reputation scoring and ban-list persistence.
+//!
+//! [`Server`](crate::server::Server) tracks a score per [`PublicKey`] (rather
+//! than per `RawFd`, so it survives reconnects) and asks the
+//! [`Delegate`](crate::server::Delegate) to grade misbehavior as a
+//! [`Penalty`] whenever it decodes a malformed or otherwise hostile frame.
+//! Scores decay back towards zero over time so a peer that misbehaved once
+//! isn't punished forever.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use cyphernet::ed25519::PublicKey;
+
+/// Graded outcome of a single piece of misbehavior, as returned alongside
+/// [`crate::server::Delegate::input`]'s actions.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Penalty {
+    /// Minor infraction: just decrement the peer's score.
+    Caution,
+    /// Tear down the current transport, but don't ban the peer outright.
+    Disconnect,
+    /// Tear down the transport and add the peer's public key to the ban set.
+    Disable,
+}
+
+impl Penalty {
+    /// How much a single occurrence of this penalty subtracts from score.
+    pub fn weight(&self) -> i32 {
+        match self {
+            Penalty::Caution => 5,
+            Penalty::Disconnect => 20,
+            Penalty::Disable => 100,
+        }
+    }
+}
+
+/// Score thresholds an operator can tune to decide when a `Caution`-only
+/// peer should be disconnected, and default decay rate.
+#[derive(Copy, Clone, Debug)]
+pub struct Thresholds {
+    /// Score at/below which a peer is disconnected even without an explicit
+    /// `Penalty::Disconnect`/`Disable`.
+    pub disconnect_at: i32,
+    /// Score at/below which a peer is additionally banned.
+    pub disable_at: i32,
+    /// Points recovered per `tick` decay pass, towards zero.
+    pub decay_per_tick: i32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            disconnect_at: -40,
+            disable_at: -150,
+            decay_per_tick: 1,
+        }
+    }
+}
+
+/// Per-[`PublicKey`] misbehavior score plus a persisted ban set, loaded from
+/// (and saved back to) a file under `~/.nsh`.
+#[derive(Debug)]
+pub struct ReputationTracker {
+    scores: std::collections::HashMap<PublicKey, i32>,
+    banned: HashSet<PublicKey>,
+    ban_file: PathBuf,
+    thresholds: Thresholds,
+}
+
+impl ReputationTracker {
+    pub fn load(ban_file: impl Into<PathBuf>, thresholds: Thresholds) -> io::Result<Self> {
+        let ban_file = ban_file.into();
+        let banned = match fs::read_to_string(&ban_file) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| {
+                    PublicKey::from_str(line.trim()).map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, "malformed ban list entry")
+                    })
+                })
+                .collect::<io::Result<HashSet<_>>>()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(ReputationTracker {
+            scores: empty!(),
+            banned,
+            ban_file,
+            thresholds,
+        })
+    }
+
+    pub fn is_banned(&self, key: &PublicKey) -> bool {
+        self.banned.contains(key)
+    }
+
+    /// Apply a [`Penalty`] for `key`, returning what the caller should do
+    /// with the peer's current transport.
+    pub fn penalize(&mut self, key: PublicKey, penalty: Penalty) -> Verdict {
+        let score = self.scores.entry(key).or_insert(0);
+        *score -= penalty.weight();
+
+        if *score <= self.thresholds.disable_at || penalty == Penalty::Disable {
+            self.banned.insert(key);
+            let _ = self.persist();
+            Verdict::Disable
+        } else if *score <= self.thresholds.disconnect_at || penalty == Penalty::Disconnect {
+            Verdict::Disconnect
+        } else {
+            Verdict::Continue
+        }
+    }
+
+    /// Decay every tracked score a little towards zero; called once per
+    /// [`reactor::Handler::tick`].
+    pub fn decay(&mut self) {
+        let step = self.thresholds.decay_per_tick;
+        self.scores.retain(|_, score| {
+            match (*score).cmp(&0) {
+                std::cmp::Ordering::Less => *score += step,
+                std::cmp::Ordering::Greater => *score -= step,
+                std::cmp::Ordering::Equal => {}
+            }
+            *score != 0
+        });
+    }
+
+    fn persist(&self) -> io::Result<()> {
+        if let Some(parent) = Path::new(&self.ban_file).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = self
+            .banned
+            .iter()
+            .map(|key| key.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(&self.ban_file, contents)
+    }
+}
+
+/// What a [`ReputationTracker::penalize`] call tells `Server` to do with the
+/// offending peer's transport.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Verdict {
+    Continue,
+    Disconnect,
+    Disable,
+}
@@ -1,44 +1,301 @@
 use cyphernet::{ed25519, x25519};
 use std::collections::{HashMap, VecDeque};
 use std::io;
-use std::net::{TcpStream, ToSocketAddrs};
 use std::os::fd::RawFd;
+use std::os::unix::net::UnixStream;
 use std::time::Duration;
 
 use netservices::{ListenerEvent, SessionEvent};
 use reactor::{Error, Resource};
 
+use crate::discovery::{self, DiscoveryMessage, RoutingTable};
+use crate::envelope::{self, FrameTag};
+use crate::forward::{ChannelFrame, ForwardSpec};
+use crate::listen::ListenAddr;
+use crate::pty::{PtyFrame, PtyHandshake};
+use crate::reputation::{Penalty, ReputationTracker, Thresholds, Verdict};
 use crate::{Session, Transport};
 
-pub type Accept = netservices::NetAccept<Session>;
+const BAN_FILE: &str = "~/.nsh/bans";
+
+/// A peer connection accepted by either listener variant, handed to
+/// [`Delegate::accept`] so the noise handshake and `Delegate` dispatch stay
+/// identical regardless of the underlying transport.
+pub enum Connection {
+    Net(std::net::TcpStream),
+    Unix(UnixStream),
+}
+
+/// Either a TCP or a Unix-domain-socket listener. The noise handshake and
+/// `Delegate` dispatch are the same either way; only how a `Connection` is
+/// accepted differs.
+pub enum Accept {
+    Net(netservices::NetAccept<Session>),
+    Unix(netservices::NetAccept<Session, UnixStream>),
+}
+
+impl Accept {
+    fn bind(listen: &ListenAddr) -> io::Result<Self> {
+        match listen {
+            ListenAddr::Net(addr) => Ok(Accept::Net(netservices::NetAccept::bind(addr)?)),
+            ListenAddr::Unix(path) => Ok(Accept::Unix(netservices::NetAccept::bind_unix(path)?)),
+        }
+    }
+}
+
+impl Resource for Accept {
+    type Id = RawFd;
+    type Event = ListenerEvent<Connection>;
+
+    fn id(&self) -> Self::Id {
+        match self {
+            Accept::Net(accept) => accept.id(),
+            Accept::Unix(accept) => accept.id(),
+        }
+    }
+}
+
+/// A human-readable peer description for logging, since a Unix socket peer
+/// has no meaningful address the way a TCP one does.
+fn describe_peer(connection: &Connection) -> String {
+    match connection {
+        Connection::Net(stream) => stream
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|_| s!("<unknown>")),
+        Connection::Unix(stream) => stream
+            .peer_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(|p| p.display().to_string()))
+            .unwrap_or_else(|| s!("<unix socket>")),
+    }
+}
+
+impl std::fmt::Display for Accept {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Accept::Net(accept) => write!(f, "{accept}"),
+            Accept::Unix(accept) => write!(f, "{accept}"),
+        }
+    }
+}
+
+/// Default hard cap on simultaneously registered transports.
+pub const DEFAULT_MAX_TRANSPORTS: usize = 256;
+/// Default soft target an operator's `tick` logging aims to stay under.
+pub const DEFAULT_IDEAL_PEERS: usize = 128;
+/// Per-peer outbox ceiling: once a queued peer has this many pending
+/// messages, further `WriteLogicError`s drop the connection instead of
+/// growing the queue further.
+pub const DEFAULT_MAX_OUTBOX_MESSAGES: usize = 256;
+/// Per-peer outbox ceiling in bytes, checked alongside
+/// [`DEFAULT_MAX_OUTBOX_MESSAGES`].
+pub const DEFAULT_MAX_OUTBOX_BYTES: usize = 8 * 1024 * 1024;
+
+/// Tunable limits guarding `Server` against unbounded memory growth from a
+/// burst of slow or hostile peers.
+#[derive(Copy, Clone, Debug)]
+pub struct Limits {
+    pub max_transports: usize,
+    pub ideal_peers: usize,
+    pub max_outbox_messages: usize,
+    pub max_outbox_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_transports: DEFAULT_MAX_TRANSPORTS,
+            ideal_peers: DEFAULT_IDEAL_PEERS,
+            max_outbox_messages: DEFAULT_MAX_OUTBOX_MESSAGES,
+            max_outbox_bytes: DEFAULT_MAX_OUTBOX_BYTES,
+        }
+    }
+}
+
 pub type Action = reactor::Action<Accept, Transport>;
 
 pub type Ecdh = x25519::PrivateKey;
 pub type Auth = ed25519::PrivateKey;
 
 pub trait Delegate: Send {
-    fn accept(&self, connection: TcpStream) -> Session;
+    fn accept(&self, connection: Connection) -> Session;
     fn new_client(&mut self, id: RawFd, key: ed25519::PublicKey) -> Vec<Action>;
-    fn input(&mut self, id: RawFd, data: Vec<u8>) -> Vec<Action>;
+
+    /// Handle incoming `data`, returning the resulting actions plus a graded
+    /// [`Penalty`] when `data` (or the behavior it represents) was abusive —
+    /// e.g. a malformed frame, a command outside what this peer is allowed,
+    /// or a flood. `Server` folds the penalty into the peer's reputation
+    /// score and tears down/bans the connection once it crosses a threshold.
+    fn input(&mut self, id: RawFd, data: Vec<u8>) -> (Vec<Action>, Option<Penalty>);
+
+    /// Allocate a PTY for `id`, export `handshake.term`/`TERMINFO` into the
+    /// child environment, apply the initial window size and spawn the
+    /// client's login shell attached to the PTY master. Implementors are
+    /// expected to keep the spawned PTY (and its master [`RawFd`]) around,
+    /// keyed by `id`, so that later [`Delegate::pty_input`] calls can find it.
+    fn spawn_pty(&mut self, id: RawFd, handshake: PtyHandshake) -> Vec<Action>;
+
+    /// Route a decoded [`PtyFrame`] to the PTY previously spawned for `id`:
+    /// write [`PtyFrame::Data`] to the master, `ioctl(TIOCSWINSZ)` on
+    /// [`PtyFrame::Resize`], and on [`PtyFrame::Exit`] is only ever sent
+    /// server -> client, so a server-side implementation need not handle it.
+    fn pty_input(&mut self, id: RawFd, frame: PtyFrame) -> Vec<Action>;
+
+    /// Handle a `--tunnel -R`/`--tunnel --udp` request from peer `id`: bind
+    /// `spec.bind` on behalf of the client (for `RemoteToLocal`) and register
+    /// a new `Accept`/`NetAccept` resource in the reactor's action queue, or
+    /// start relaying datagrams (for `Udp`). Implementors keep the resulting
+    /// listeners/sockets keyed by the [`crate::forward::ChannelId`]s they
+    /// assign so that [`Delegate::channel_input`] can route to them.
+    fn open_forward(&mut self, id: RawFd, spec: ForwardSpec) -> Vec<Action>;
+
+    /// Route a decoded [`ChannelFrame`] belonging to a forward previously
+    /// opened with [`Delegate::open_forward`] to its listener/socket.
+    fn channel_input(&mut self, id: RawFd, frame: ChannelFrame) -> Vec<Action>;
+
+    /// Spawn `program args` on behalf of peer `id` with piped stdio, pumping
+    /// each pipe into `Action::Send(id, ExecFrame::{Stdout,Stderr}.encode())`
+    /// as it produces output and, once the child is reaped, emitting
+    /// `ExecFrame::ExitStatus`.
+    fn spawn_exec(&mut self, id: RawFd, program: String, args: Vec<String>) -> Vec<Action>;
+
+    /// Observe a [`DiscoveryMessage`] from peer `id`. `Server` has already
+    /// answered `Ping`/`FindNode` and folded `Nodes` into its own
+    /// [`crate::discovery::RoutingTable`] before calling this (see
+    /// `Server::handle_discovery`); any actions returned are queued in
+    /// addition to those replies.
+    fn discovery_input(&mut self, id: RawFd, msg: DiscoveryMessage) -> Vec<Action>;
 }
 
 pub struct Server<D: Delegate> {
     outbox: HashMap<RawFd, VecDeque<Vec<u8>>>,
     action_queue: VecDeque<Action>,
     delegate: D,
+    /// `RawFd -> PublicKey` for every established connection, so a penalty
+    /// reported against a `RawFd` can be folded into the peer's long-lived,
+    /// reconnect-surviving reputation score.
+    peer_keys: HashMap<RawFd, ed25519::PublicKey>,
+    reputation: ReputationTracker,
+    /// This node's view of the Kademlia DHT, loaded from (and, on every
+    /// mutation, persisted back to) [`discovery::default_table_path`] so a
+    /// restarted daemon keeps what it previously discovered.
+    routing: RoutingTable,
+    limits: Limits,
+    /// Live, registered transport count, tracked as connections register and
+    /// unregister rather than queried from the reactor.
+    transport_count: usize,
 }
 
 impl<D: Delegate> Server<D> {
-    pub fn with(listen: &impl ToSocketAddrs, delegate: D) -> io::Result<Self> {
+    pub fn with(listen: &ListenAddr, delegate: D, self_id: ed25519::PublicKey) -> io::Result<Self> {
+        Self::with_limits(listen, delegate, Limits::default(), self_id)
+    }
+
+    pub fn with_limits(
+        listen: &ListenAddr,
+        delegate: D,
+        limits: Limits,
+        self_id: ed25519::PublicKey,
+    ) -> io::Result<Self> {
         let mut action_queue = VecDeque::new();
         let listener = Accept::bind(listen)?;
         action_queue.push_back(Action::RegisterListener(listener));
+        let ban_file = shellexpand::tilde(BAN_FILE).to_string();
+        let reputation = ReputationTracker::load(ban_file, Thresholds::default())?;
+        let routing = RoutingTable::load(self_id, discovery::default_table_path())?;
         Ok(Self {
             outbox: empty!(),
             action_queue,
             delegate,
+            peer_keys: empty!(),
+            reputation,
+            routing,
+            limits,
+            transport_count: 0,
         })
     }
+
+    /// Apply `penalty` to `key`'s score and act on the resulting [`Verdict`].
+    fn apply_penalty(&mut self, id: RawFd, key: ed25519::PublicKey, penalty: Penalty) {
+        match self.reputation.penalize(key, penalty) {
+            Verdict::Continue => {}
+            Verdict::Disconnect => {
+                log::warn!(target: "server", "Peer {key}@{id} crossed the disconnect threshold, dropping connection");
+                self.unregister(id);
+            }
+            Verdict::Disable => {
+                log::warn!(target: "server", "Peer {key}@{id} crossed the ban threshold, disconnecting and banning");
+                self.unregister(id);
+            }
+        }
+    }
+
+    /// Queue `id`'s transport for removal and keep `transport_count` in sync.
+    fn unregister(&mut self, id: RawFd) {
+        self.peer_keys.remove(&id);
+        self.outbox.remove(&id);
+        self.transport_count = self.transport_count.saturating_sub(1);
+        self.action_queue.push_back(Action::UnregisterTransport(id));
+    }
+
+    /// Demultiplex an inbound [`SessionEvent::Data`] payload by its
+    /// [`FrameTag`], routing [`FrameTag::Forward`]/[`FrameTag::Channel`]
+    /// frames to the matching `Delegate` method and [`FrameTag::Discovery`]
+    /// messages to [`Self::handle_discovery`]. Anything untagged (or tagged
+    /// with a byte that doesn't parse as a [`FrameTag`], e.g. ordinary
+    /// [`crate::command::Command`] text) falls back to `delegate.input`, the
+    /// pre-existing untagged protocol.
+    fn dispatch_data(&mut self, id: RawFd, data: Vec<u8>) -> (Vec<Action>, Option<Penalty>) {
+        match envelope::unwrap(&data) {
+            Ok((FrameTag::Forward, _)) => match ForwardSpec::decode_request(&data) {
+                Ok(spec) => (self.delegate.open_forward(id, spec), None),
+                Err(_) => (vec![], Some(Penalty::Caution)),
+            },
+            Ok((FrameTag::Channel, _)) => match ChannelFrame::decode(&data) {
+                Ok(frame) => (self.delegate.channel_input(id, frame), None),
+                Err(_) => (vec![], Some(Penalty::Caution)),
+            },
+            Ok((FrameTag::Discovery, body)) => match DiscoveryMessage::decode(body) {
+                Ok(msg) => {
+                    let mut actions = self.handle_discovery(id, &msg);
+                    actions.extend(self.delegate.discovery_input(id, msg));
+                    (actions, None)
+                }
+                Err(_) => (vec![], Some(Penalty::Caution)),
+            },
+            Ok((FrameTag::PtyHandshake, _))
+            | Ok((FrameTag::Pty, _))
+            | Ok((FrameTag::Command, _))
+            | Err(_) => self.delegate.input(id, data),
+        }
+    }
+
+    /// This node's own authoritative handling of a [`DiscoveryMessage`]:
+    /// answer liveness/lookup requests from `self.routing`, and fold `Nodes`
+    /// replies back into it (a `Pong` carries no address, so it changes
+    /// nothing). Run in addition to (before)
+    /// `delegate.discovery_input`, since populating/persisting the table is
+    /// `Server`'s job (it owns `routing`), the same way it — not
+    /// `Delegate` — owns and persists `reputation`.
+    fn handle_discovery(&mut self, id: RawFd, msg: &DiscoveryMessage) -> Vec<Action> {
+        match msg {
+            DiscoveryMessage::Ping => {
+                vec![send(id, FrameTag::Discovery, &DiscoveryMessage::Pong)]
+            }
+            DiscoveryMessage::Pong => vec![],
+            DiscoveryMessage::FindNode(target) => {
+                let nodes = DiscoveryMessage::Nodes(self.routing.closest(target, discovery::K));
+                vec![send(id, FrameTag::Discovery, &nodes)]
+            }
+            DiscoveryMessage::Nodes(entries) => {
+                for entry in entries {
+                    self.routing.insert(entry.id, entry.addr.clone());
+                }
+                vec![]
+            }
+        }
+    }
 }
 
 impl<D: Delegate> reactor::Handler for Server<D> {
@@ -48,6 +305,22 @@ impl<D: Delegate> reactor::Handler for Server<D> {
 
     fn tick(&mut self, time: Duration) {
         log::trace!(target: "server", "reactor ticks at {time:?}");
+        self.reputation.decay();
+        // A full bucket's least-recently-seen candidate is only evicted once
+        // it's no longer connected; `Server` has no way to dial an arbitrary
+        // candidate just to liveness-`PING` it, so "currently connected"
+        // stands in for "known alive".
+        for (bucket_idx, candidate) in self.routing.stale_candidates() {
+            let connected = self.peer_keys.values().any(|key| *key == candidate.id);
+            if !connected {
+                self.routing.evict_stale(bucket_idx);
+            }
+        }
+        if self.transport_count > self.limits.ideal_peers {
+            log::info!(target: "server", "{}/{} transports (ideal {}, hard cap {})", self.transport_count, self.limits.max_transports, self.limits.ideal_peers, self.limits.max_transports);
+        } else {
+            log::trace!(target: "server", "{}/{} transports", self.transport_count, self.limits.max_transports);
+        }
     }
 
     fn handle_timer(&mut self) {
@@ -63,17 +336,21 @@ impl<D: Delegate> reactor::Handler for Server<D> {
         log::trace!(target: "server", "Listener event on {id} at {time:?}");
         match event {
             ListenerEvent::Accepted(connection) => {
-                let peer_addr = connection
-                    .peer_addr()
-                    .expect("unknown peer address on accepted connection");
-                let local_addr = connection
-                    .local_addr()
-                    .expect("unknown local address on accepted connection");
-                log::info!(target: "server", "Incoming connection from {peer_addr} on {local_addr}");
+                let peer_addr = describe_peer(&connection);
+                log::info!(target: "server", "Incoming connection from {peer_addr}");
+                if self.transport_count >= self.limits.max_transports {
+                    log::warn!(target: "server", "At capacity ({}/{} transports), dropping connection from {peer_addr}", self.transport_count, self.limits.max_transports);
+                    return;
+                }
+                // A banned peer's public key isn't known until the noise
+                // handshake authenticates it, so the earliest we can reject
+                // it is right after `SessionEvent::Established` below rather
+                // than here.
                 let session = self.delegate.accept(connection);
                 match Transport::accept(session) {
                     Ok(transport) => {
                         log::info!(target: "server", "Connection accepted, registering transport with reactor");
+                        self.transport_count += 1;
                         self.action_queue
                             .push_back(Action::RegisterTransport(transport));
                     }
@@ -98,19 +375,37 @@ impl<D: Delegate> reactor::Handler for Server<D> {
         match event {
             SessionEvent::Established(artifact) => {
                 let key = artifact.state.pk;
+                if self.reputation.is_banned(&key) {
+                    log::warn!(target: "server", "Rejecting banned peer {key}@{id}");
+                    self.unregister(id);
+                    return;
+                }
+                self.peer_keys.insert(id, key);
                 let queue = self.outbox.remove(&id).unwrap_or_default();
                 log::debug!(target: "server", "Connection with remote peer {key}@{id} successfully established; processing {} items from outbox", queue.len());
                 self.action_queue.extend(self.delegate.new_client(id, key));
                 self.action_queue
-                    .extend(queue.into_iter().map(|msg| Action::Send(id, msg)))
+                    .extend(queue.into_iter().map(|msg| Action::Send(id, msg)));
+                // Bootstrap this node's own routing table by asking every
+                // newly established peer who's closest to us.
+                let self_id = self.routing.self_id();
+                self.action_queue.push_back(send(
+                    id,
+                    FrameTag::Discovery,
+                    &DiscoveryMessage::FindNode(self_id),
+                ));
             }
             SessionEvent::Data(data) => {
                 log::trace!(target: "server", "Incoming data {data:?}");
-                self.action_queue.extend(self.delegate.input(id, data));
+                let (actions, penalty) = self.dispatch_data(id, data);
+                self.action_queue.extend(actions);
+                if let (Some(penalty), Some(&key)) = (penalty, self.peer_keys.get(&id)) {
+                    self.apply_penalty(id, key, penalty);
+                }
             }
             SessionEvent::Terminated(err) => {
                 log::error!(target: "server", "Connection with {id} is terminated due to an error: {err}");
-                self.action_queue.push_back(Action::UnregisterTransport(id));
+                self.unregister(id);
             }
         }
     }
@@ -126,8 +421,18 @@ impl<D: Delegate> reactor::Handler for Server<D> {
                 return;
             }
             Error::WriteLogicError(id, msg) => {
-                log::debug!(target: "server", "Remote peer {id} is not ready, putting message to outbox");
-                self.outbox.entry(id).or_default().push_back(msg)
+                let existing = self.outbox.get(&id);
+                let queued_len = existing.map_or(0, VecDeque::len);
+                let queued_bytes: usize = existing.map_or(0, |q| q.iter().map(Vec::len).sum());
+                let over_limit = queued_len >= self.limits.max_outbox_messages
+                    || queued_bytes + msg.len() > self.limits.max_outbox_bytes;
+                if over_limit {
+                    log::warn!(target: "server", "Peer {id}'s outbox exceeded its backpressure limit ({queued_len} messages, {queued_bytes} bytes), dropping connection");
+                    self.unregister(id);
+                } else {
+                    log::debug!(target: "server", "Remote peer {id} is not ready, putting message to outbox ({}/{})", queued_len + 1, self.limits.max_outbox_messages);
+                    self.outbox.entry(id).or_default().push_back(msg);
+                }
             }
             // All others are errors:
             ref err @ Error::ListenerUnknown(_)
@@ -142,7 +447,7 @@ impl<D: Delegate> reactor::Handler for Server<D> {
             }
             ref err @ Error::WriteFailure(id, _) | ref err @ Error::TransportPollError(id, _) => {
                 log::error!(target: "server", "Error: {err}");
-                self.action_queue.push_back(Action::UnregisterTransport(id));
+                self.unregister(id);
             }
         }
     }
@@ -164,3 +469,125 @@ impl<D: Delegate> Iterator for Server<D> {
         self.action_queue.pop_front()
     }
 }
+
+/// Envelope-tag and encode `msg`, ready to hand back as an [`Action::Send`].
+fn send(id: RawFd, tag: FrameTag, msg: &DiscoveryMessage) -> Action {
+    Action::Send(id, envelope::wrap(tag, &msg.encode()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forward::ForwardDirection;
+
+    /// A no-op [`Delegate`] that only records which of `input`/`channel_input`/
+    /// `open_forward` it was called with, so [`Server::dispatch_data`] can be exercised
+    /// directly without the noise handshake/reactor machinery the other
+    /// `Delegate` methods need.
+    #[derive(Default)]
+    struct RecordingDelegate {
+        input_called: bool,
+        channel_input_called: bool,
+        open_forward_called: bool,
+    }
+
+    impl Delegate for RecordingDelegate {
+        fn accept(&self, _connection: Connection) -> Session {
+            unimplemented!("dispatch_data never calls accept")
+        }
+
+        fn new_client(&mut self, _id: RawFd, _key: ed25519::PublicKey) -> Vec<Action> {
+            vec![]
+        }
+
+        fn input(&mut self, _id: RawFd, _data: Vec<u8>) -> (Vec<Action>, Option<Penalty>) {
+            self.input_called = true;
+            (vec![], None)
+        }
+
+        fn spawn_pty(&mut self, _id: RawFd, _handshake: PtyHandshake) -> Vec<Action> {
+            vec![]
+        }
+
+        fn pty_input(&mut self, _id: RawFd, _frame: PtyFrame) -> Vec<Action> {
+            vec![]
+        }
+
+        fn open_forward(&mut self, _id: RawFd, _spec: ForwardSpec) -> Vec<Action> {
+            self.open_forward_called = true;
+            vec![]
+        }
+
+        fn channel_input(&mut self, _id: RawFd, _frame: ChannelFrame) -> Vec<Action> {
+            self.channel_input_called = true;
+            vec![]
+        }
+
+        fn spawn_exec(&mut self, _id: RawFd, _program: String, _args: Vec<String>) -> Vec<Action> {
+            vec![]
+        }
+
+        fn discovery_input(&mut self, _id: RawFd, _msg: DiscoveryMessage) -> Vec<Action> {
+            vec![]
+        }
+    }
+
+    /// Build a `Server` with a [`RecordingDelegate`], bypassing
+    /// [`Server::with_limits`] (and the real listener bind/reputation-file/
+    /// routing-table I/O it does) since `dispatch_data` never touches any of
+    /// that; `reputation`/`routing` are still given placeholder paths since
+    /// `Server` requires them. `ReputationTracker::load` only probes the
+    /// (absent) placeholder, so the ban list starts empty, and none of these
+    /// tests writes to either path.
+    fn test_server(delegate: RecordingDelegate) -> Server<RecordingDelegate> {
+        let self_id = ed25519::PublicKey::try_from([1u8; 32].as_slice()).unwrap();
+        let placeholder = std::env::temp_dir().join("nsh-server-dispatch-test-unused");
+        Server {
+            outbox: empty!(),
+            action_queue: VecDeque::new(),
+            delegate,
+            peer_keys: empty!(),
+            reputation: ReputationTracker::load(placeholder.clone(), Thresholds::default())
+                .unwrap(),
+            routing: RoutingTable::new(self_id, placeholder),
+            limits: Limits::default(),
+            transport_count: 0,
+        }
+    }
+
+    #[test]
+    fn dispatch_data_routes_channel_frames_to_channel_input() {
+        let mut server = test_server(RecordingDelegate::default());
+        let frame = ChannelFrame::Open { channel: 1 };
+        let (actions, penalty) = server.dispatch_data(7, frame.encode());
+        assert!(actions.is_empty());
+        assert!(penalty.is_none());
+        assert!(server.delegate.channel_input_called);
+        assert!(!server.delegate.input_called);
+    }
+
+    #[test]
+    fn dispatch_data_falls_back_to_input_for_untagged_data() {
+        let mut server = test_server(RecordingDelegate::default());
+        let (_, penalty) = server.dispatch_data(7, b"command hello".to_vec());
+        assert!(penalty.is_none());
+        assert!(server.delegate.input_called);
+        assert!(!server.delegate.channel_input_called);
+    }
+
+    #[test]
+    fn dispatch_data_routes_forward_requests_to_open_forward() {
+        let mut server = test_server(RecordingDelegate::default());
+        let spec = ForwardSpec {
+            direction: ForwardDirection::LocalToRemote,
+            protocol: crate::forward::ForwardProtocol::Tcp,
+            bind: "127.0.0.1:9000".parse().unwrap(),
+            target: "127.0.0.1:9001".parse().unwrap(),
+        };
+        let (_, penalty) = server.dispatch_data(7, spec.encode_request());
+        assert!(penalty.is_none());
+        assert!(server.delegate.open_forward_called);
+        assert!(!server.delegate.channel_input_called);
+        assert!(!server.delegate.input_called);
+    }
+}